@@ -0,0 +1,20 @@
+use std::time::Duration;
+use yew::services::interval::IntervalService;
+use yew::services::Task;
+use yew::Callback;
+
+pub struct TickService {
+    interval_service: IntervalService,
+}
+
+impl TickService {
+    pub fn new() -> Self {
+        TickService {
+            interval_service: IntervalService::new(),
+        }
+    }
+
+    pub fn spawn(&mut self, duration: Duration, callback: Callback<()>) -> Box<dyn Task> {
+        Box::new(self.interval_service.spawn(duration, callback))
+    }
+}